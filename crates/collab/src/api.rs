@@ -1,24 +1,110 @@
 use crate::{
     auth,
-    db::{InviteCode, User, UserId},
+    db::{AccessToken, Event, InviteCode, User, UserId},
     AppState, Error, Result,
 };
 use anyhow::anyhow;
 use axum::{
     body::Body,
     extract::{Path, Query},
-    http::{self, Request, StatusCode},
+    http::{self, HeaderMap, Request, StatusCode},
     middleware::{self, Next},
     response::IntoResponse,
-    routing::{get, post, put},
+    routing::{delete, get, post, put},
     Extension, Json, Router,
 };
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
 use nanoid::nanoid;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use serde_json::json;
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
 use tower::ServiceBuilder;
 use tracing::instrument;
 
+/// Pulls the originating client IP out of a reverse-proxy header, for
+/// attribution in the audit log. We sit behind a load balancer, so
+/// `ConnectInfo` would only ever see its address.
+fn client_ip(headers: &HeaderMap) -> Option<String> {
+    // The leftmost entry is whatever the client itself sent and is trivially
+    // spoofable; our load balancer appends rather than replaces, so the
+    // rightmost entry is the one *it* set and is the only hop we trust.
+    headers
+        .get("x-forwarded-for")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.rsplit(',').next())
+        .map(|value| value.trim().to_string())
+}
+
+/// How much clock drift between the server that signed a token and the
+/// server validating it we're willing to tolerate.
+const CLOCK_SKEW_LEEWAY_SECS: u64 = 60;
+
+/// How long a freshly-minted access token is valid for.
+const ACCESS_TOKEN_TTL_SECS: i64 = 60 * 60 * 24 * 30;
+
+/// A scope that implicitly grants every other scope.
+const SCOPE_ADMIN: &str = "admin";
+
+/// The claims carried by the signed, scoped access tokens that replace the
+/// old single shared `api_token`. Decoded once by [`validate_api_token`] and
+/// then made available to handlers via [`Extension`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: UserId,
+    /// The id of the [`AccessToken`] record this JWT was minted for, so we
+    /// can look up whether it's since been individually revoked and update
+    /// its `last_used_at`.
+    pub jti: i32,
+    pub scopes: Vec<String>,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+impl Claims {
+    fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|s| s == scope || s == SCOPE_ADMIN)
+    }
+}
+
+/// Mints a signed JWT for the access token record `jti`, belonging to `sub`
+/// and carrying `scopes`, valid from now for [`ACCESS_TOKEN_TTL_SECS`]
+/// seconds.
+fn sign_access_token(secret: &str, sub: UserId, jti: i32, scopes: Vec<String>) -> Result<String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let claims = Claims {
+        sub,
+        jti,
+        scopes,
+        iat: now,
+        exp: now + ACCESS_TOKEN_TTL_SECS,
+    };
+    encode(
+        &Header::new(Algorithm::HS256),
+        &claims,
+        &EncodingKey::from_secret(secret.as_bytes()),
+    )
+    .map_err(|error| anyhow!("failed to sign access token: {error}").into())
+}
+
+/// Returns a `403` if `claims` doesn't carry `scope` (or `admin`, which
+/// implies every scope).
+fn require_scope(claims: &Claims, scope: &str) -> Result<()> {
+    if claims.has_scope(scope) {
+        Ok(())
+    } else {
+        Err(Error::Http(
+            StatusCode::FORBIDDEN,
+            format!("access token is missing the `{scope}` scope"),
+        ))
+    }
+}
+
 pub fn routes(state: Arc<AppState>) -> Router<Body> {
     Router::new()
         .route("/users", get(get_users).post(create_user))
@@ -26,13 +112,26 @@ pub fn routes(state: Arc<AppState>) -> Router<Body> {
             "/users/:login",
             get(get_user).put(update_user).delete(destroy_user),
         )
-        .route("/users/:login/access_tokens", post(create_access_token))
+        .route(
+            "/users/:login/access_tokens",
+            get(get_access_tokens).post(create_access_token),
+        )
+        .route(
+            "/users/:login/access_tokens/:id",
+            delete(revoke_access_token),
+        )
+        .route("/users/:login/disable", put(disable_user))
+        .route("/users/:login/enable", put(enable_user))
+        .route("/users/:login/deauth", post(deauth_user))
         .route(
             "/users/:id/invite_codes",
             get(get_invite_codes).post(create_invite_code),
         )
         .route("/invite_codes/:code", put(update_invite_code))
         .route("/panic", post(trace_panic))
+        .route("/diagnostics", get(get_diagnostics))
+        .route("/test_smtp", post(test_smtp))
+        .route("/events", get(get_events))
         .layer(
             ServiceBuilder::new()
                 .layer(Extension(state))
@@ -40,7 +139,7 @@ pub fn routes(state: Arc<AppState>) -> Router<Body> {
         )
 }
 
-pub async fn validate_api_token<B>(req: Request<B>, next: Next<B>) -> impl IntoResponse {
+pub async fn validate_api_token<B>(mut req: Request<B>, next: Next<B>) -> impl IntoResponse {
     let token = req
         .headers()
         .get(http::header::AUTHORIZATION)
@@ -51,29 +150,113 @@ pub async fn validate_api_token<B>(req: Request<B>, next: Next<B>) -> impl IntoR
                 "missing authorization header".to_string(),
             )
         })?
-        .strip_prefix("token ")
+        .strip_prefix("Bearer ")
         .ok_or_else(|| {
             Error::Http(
                 StatusCode::BAD_REQUEST,
                 "invalid authorization header".to_string(),
             )
-        })?;
+        })?
+        .to_string();
+
+    let state = req.extensions().get::<Arc<AppState>>().unwrap().clone();
 
-    let state = req.extensions().get::<Arc<AppState>>().unwrap();
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.leeway = CLOCK_SKEW_LEEWAY_SECS;
+    let claims = decode::<Claims>(
+        &token,
+        &DecodingKey::from_secret(state.jwt_secret.as_bytes()),
+        &validation,
+    )
+    .map_err(|_| {
+        Error::Http(
+            StatusCode::UNAUTHORIZED,
+            "invalid or expired access token".to_string(),
+        )
+    })?
+    .claims;
 
-    if token != state.api_token {
+    // The signature and expiry check out, but the user it was issued to may
+    // have since been removed or deactivated.
+    let user = state
+        .db
+        .get_user_by_id(claims.sub)
+        .await?
+        .ok_or_else(|| {
+            Error::Http(
+                StatusCode::UNAUTHORIZED,
+                "invalid or expired access token".to_string(),
+            )
+        })?;
+    if user.deactivated {
+        Err(Error::Http(
+            StatusCode::UNAUTHORIZED,
+            "this account has been disabled".to_string(),
+        ))?
+    }
+
+    // The individual token may have been revoked even though the account as
+    // a whole is still active.
+    if state.db.is_access_token_revoked(claims.jti).await? {
         Err(Error::Http(
             StatusCode::UNAUTHORIZED,
-            "invalid authorization token".to_string(),
+            "this access token has been revoked".to_string(),
         ))?
     }
+    state.db.touch_access_token(claims.jti).await?;
+
+    req.extensions_mut().insert(claims);
 
     Ok::<_, Error>(next.run(req).await)
 }
 
-async fn get_users(Extension(app): Extension<Arc<AppState>>) -> Result<Json<Vec<User>>> {
-    let users = app.db.get_all_users().await?;
-    Ok(Json(users))
+#[derive(Deserialize)]
+struct GetUsersParams {
+    limit: Option<u32>,
+    before: Option<i32>,
+    after: Option<i32>,
+    github_login_contains: Option<String>,
+    admin: Option<bool>,
+    deactivated: Option<bool>,
+}
+
+#[derive(Serialize)]
+struct GetUsersResponse {
+    users: Vec<User>,
+    next_cursor: Option<UserId>,
+    total: usize,
+}
+
+async fn get_users(
+    Query(params): Query<GetUsersParams>,
+    Extension(app): Extension<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<GetUsersResponse>> {
+    require_scope(&claims, SCOPE_ADMIN)?;
+
+    let limit = params.limit.unwrap_or(50).min(200);
+    let (users, total) = app
+        .db
+        .get_users_paginated(
+            params.before.map(UserId),
+            params.after.map(UserId),
+            params.github_login_contains,
+            params.admin,
+            params.deactivated,
+            limit,
+        )
+        .await?;
+    let next_cursor = if users.len() as u32 == limit {
+        users.last().map(|user| user.id)
+    } else {
+        None
+    };
+
+    Ok(Json(GetUsersResponse {
+        users,
+        next_cursor,
+        total,
+    }))
 }
 
 #[derive(Deserialize)]
@@ -83,9 +266,13 @@ struct CreateUserParams {
 }
 
 async fn create_user(
+    headers: HeaderMap,
     Json(params): Json<CreateUserParams>,
     Extension(app): Extension<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
 ) -> Result<Json<User>> {
+    require_scope(&claims, "users:write")?;
+
     let user_id = app
         .db
         .create_user(&params.github_login, params.admin)
@@ -97,6 +284,16 @@ async fn create_user(
         .await?
         .ok_or_else(|| anyhow!("couldn't find the user we just created"))?;
 
+    app.db
+        .log_event(
+            claims.sub,
+            "user.create",
+            user_id.to_string(),
+            json!({ "github_login": params.github_login, "admin": params.admin }),
+            client_ip(&headers),
+        )
+        .await?;
+
     Ok(Json(user))
 }
 
@@ -106,21 +303,136 @@ struct UpdateUserParams {
 }
 
 async fn update_user(
+    headers: HeaderMap,
     Path(user_id): Path<i32>,
     Json(params): Json<UpdateUserParams>,
     Extension(app): Extension<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
 ) -> Result<()> {
+    require_scope(&claims, SCOPE_ADMIN)?;
+
     app.db
         .set_user_is_admin(UserId(user_id), params.admin)
         .await?;
+
+    app.db
+        .log_event(
+            claims.sub,
+            "user.set_admin",
+            user_id.to_string(),
+            json!({ "admin": params.admin }),
+            client_ip(&headers),
+        )
+        .await?;
+
     Ok(())
 }
 
 async fn destroy_user(
+    headers: HeaderMap,
     Path(user_id): Path<i32>,
     Extension(app): Extension<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
 ) -> Result<()> {
+    require_scope(&claims, SCOPE_ADMIN)?;
+
     app.db.destroy_user(UserId(user_id)).await?;
+
+    app.db
+        .log_event(
+            claims.sub,
+            "user.destroy",
+            user_id.to_string(),
+            json!({}),
+            client_ip(&headers),
+        )
+        .await?;
+
+    Ok(())
+}
+
+async fn disable_user(
+    headers: HeaderMap,
+    Path(login): Path<String>,
+    Extension(app): Extension<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+) -> Result<()> {
+    require_scope(&claims, SCOPE_ADMIN)?;
+
+    let user = app
+        .db
+        .get_user_by_github_login(&login)
+        .await?
+        .ok_or_else(|| anyhow!("user not found"))?;
+    app.db.set_user_deactivated(user.id, true).await?;
+    app.db.revoke_access_tokens(user.id).await?;
+
+    app.db
+        .log_event(
+            claims.sub,
+            "user.disable",
+            user.id.to_string(),
+            json!({}),
+            client_ip(&headers),
+        )
+        .await?;
+
+    Ok(())
+}
+
+async fn enable_user(
+    headers: HeaderMap,
+    Path(login): Path<String>,
+    Extension(app): Extension<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+) -> Result<()> {
+    require_scope(&claims, SCOPE_ADMIN)?;
+
+    let user = app
+        .db
+        .get_user_by_github_login(&login)
+        .await?
+        .ok_or_else(|| anyhow!("user not found"))?;
+    app.db.set_user_deactivated(user.id, false).await?;
+
+    app.db
+        .log_event(
+            claims.sub,
+            "user.enable",
+            user.id.to_string(),
+            json!({}),
+            client_ip(&headers),
+        )
+        .await?;
+
+    Ok(())
+}
+
+async fn deauth_user(
+    headers: HeaderMap,
+    Path(login): Path<String>,
+    Extension(app): Extension<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+) -> Result<()> {
+    require_scope(&claims, SCOPE_ADMIN)?;
+
+    let user = app
+        .db
+        .get_user_by_github_login(&login)
+        .await?
+        .ok_or_else(|| anyhow!("user not found"))?;
+    app.db.revoke_access_tokens(user.id).await?;
+
+    app.db
+        .log_event(
+            claims.sub,
+            "user.deauth",
+            user.id.to_string(),
+            json!({}),
+            client_ip(&headers),
+        )
+        .await?;
+
     Ok(())
 }
 
@@ -146,6 +458,14 @@ struct UserWithInviteCodes {
 #[derive(Deserialize)]
 struct CreateInviteCodeParams {
     allowed_usage_count: u32,
+    email: Option<String>,
+}
+
+#[derive(Serialize)]
+struct CreateInviteCodeResponse {
+    #[serde(flatten)]
+    invite_code: InviteCode,
+    delivered: bool,
 }
 
 async fn get_invite_codes(
@@ -156,14 +476,60 @@ async fn get_invite_codes(
 }
 
 async fn create_invite_code(
+    headers: HeaderMap,
     Path(user_id): Path<i32>,
     Json(params): Json<CreateInviteCodeParams>,
     Extension(app): Extension<Arc<AppState>>,
-) -> Result<()> {
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<CreateInviteCodeResponse>> {
+    require_scope(&claims, "invites:write")?;
+
+    let code = nanoid!(16);
     app.db
-        .create_invite_code(UserId(user_id), &nanoid!(16), params.allowed_usage_count)
+        .create_invite_code(UserId(user_id), &code, params.allowed_usage_count)
         .await?;
-    Ok(())
+
+    app.db
+        .log_event(
+            claims.sub,
+            "invite_code.create",
+            code.clone(),
+            json!({
+                "for_user_id": user_id,
+                "allowed_usage_count": params.allowed_usage_count,
+                "email": params.email,
+            }),
+            client_ip(&headers),
+        )
+        .await?;
+
+    // Sending mail is best-effort: the code is valid and usable even if we
+    // fail to deliver it, so a dead mail server shouldn't roll back invite
+    // creation.
+    let mut delivered = false;
+    if let Some(email) = &params.email {
+        app.db.set_invite_code_recipient(&code, email).await?;
+        match app.mailer.send_invite_email(email, &code).await {
+            Ok(()) => {
+                app.db.mark_invite_code_sent(&code).await?;
+                delivered = true;
+            }
+            Err(error) => {
+                tracing::error!(%error, %email, "failed to send invite email");
+            }
+        }
+    }
+
+    let invite_code = app
+        .db
+        .get_invite_code(&code)
+        .await?
+        .ok_or_else(|| anyhow!("couldn't find the invite code we just created"))?;
+
+    Ok(Json(CreateInviteCodeResponse {
+        invite_code,
+        delivered,
+    }))
 }
 
 #[derive(Deserialize)]
@@ -172,13 +538,28 @@ struct UpdateInviteCodeParams {
 }
 
 async fn update_invite_code(
+    headers: HeaderMap,
     Path(code): Path<String>,
     Json(params): Json<UpdateInviteCodeParams>,
     Extension(app): Extension<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
 ) -> Result<()> {
+    require_scope(&claims, "invites:write")?;
+
     app.db
         .update_invite_code(&code, params.remaining_count)
         .await?;
+
+    app.db
+        .log_event(
+            claims.sub,
+            "invite_code.redeem",
+            code,
+            json!({ "remaining_count": params.remaining_count }),
+            client_ip(&headers),
+        )
+        .await?;
+
     Ok(())
 }
 
@@ -194,10 +575,154 @@ async fn trace_panic(panic: Json<Panic>) -> Result<()> {
     Ok(())
 }
 
+#[derive(Serialize)]
+struct Diagnostics {
+    build_version: &'static str,
+    uptime_secs: u64,
+    database: Option<DatabaseDiagnostics>,
+    dependencies: Vec<DependencyDiagnostics>,
+}
+
+#[derive(Serialize)]
+struct DatabaseDiagnostics {
+    server_version: String,
+    /// `None` when we couldn't determine migration state (e.g. the
+    /// migrations table itself is unreadable) — that's itself a finding
+    /// worth surfacing, not a reason to fail the whole health check.
+    migrations_up_to_date: Option<bool>,
+    pending_migrations: Option<Vec<String>>,
+    pool_size: u32,
+    pool_idle: u32,
+}
+
+#[derive(Serialize)]
+struct DependencyDiagnostics {
+    name: &'static str,
+    reachable: bool,
+    latency_ms: u128,
+}
+
+async fn get_diagnostics(
+    Extension(app): Extension<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<Diagnostics>> {
+    require_scope(&claims, SCOPE_ADMIN)?;
+
+    let start = std::time::Instant::now();
+    let server_version = app.db.server_version().await;
+    let db_latency_ms = start.elapsed().as_millis();
+    let db_reachable = server_version.is_ok();
+
+    let database = if let Ok(server_version) = server_version {
+        // Migration state is itself a diagnostic we're trying to surface, so
+        // a failure reading it should show up as "unknown", not 500 the
+        // whole endpoint.
+        let pending_migrations = match app.db.pending_migrations().await {
+            Ok(pending_migrations) => Some(pending_migrations),
+            Err(error) => {
+                tracing::error!(%error, "failed to read migration state for diagnostics");
+                None
+            }
+        };
+        let pool_status = app.db.pool_status();
+        Some(DatabaseDiagnostics {
+            server_version,
+            migrations_up_to_date: pending_migrations
+                .as_ref()
+                .map(|pending_migrations| pending_migrations.is_empty()),
+            pending_migrations,
+            pool_size: pool_status.size,
+            pool_idle: pool_status.idle,
+        })
+    } else {
+        None
+    };
+
+    let start = std::time::Instant::now();
+    let mailer_reachable = app.mailer.check_connection().await.is_ok();
+    let mailer_latency_ms = start.elapsed().as_millis();
+
+    Ok(Json(Diagnostics {
+        build_version: env!("CARGO_PKG_VERSION"),
+        uptime_secs: app.start_time.elapsed().as_secs(),
+        database,
+        dependencies: vec![
+            DependencyDiagnostics {
+                name: "database",
+                reachable: db_reachable,
+                latency_ms: db_latency_ms,
+            },
+            DependencyDiagnostics {
+                name: "mailer",
+                reachable: mailer_reachable,
+                latency_ms: mailer_latency_ms,
+            },
+        ],
+    }))
+}
+
+#[derive(Deserialize)]
+struct GetEventsParams {
+    actor_id: Option<i32>,
+    action: Option<String>,
+    after: Option<i64>,
+    before: Option<i64>,
+    before_id: Option<i64>,
+    limit: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct GetEventsResponse {
+    events: Vec<Event>,
+    next_cursor: Option<i64>,
+}
+
+async fn get_events(
+    Query(params): Query<GetEventsParams>,
+    Extension(app): Extension<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<GetEventsResponse>> {
+    require_scope(&claims, SCOPE_ADMIN)?;
+
+    let limit = params.limit.unwrap_or(100).min(500);
+    let (events, next_cursor) = app
+        .db
+        .get_events(
+            params.actor_id.map(UserId),
+            params.action,
+            params.after,
+            params.before,
+            params.before_id,
+            limit,
+        )
+        .await?;
+
+    Ok(Json(GetEventsResponse {
+        events,
+        next_cursor,
+    }))
+}
+
+#[derive(Deserialize)]
+struct TestSmtpParams {
+    to: String,
+}
+
+async fn test_smtp(
+    Json(params): Json<TestSmtpParams>,
+    Extension(app): Extension<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+) -> Result<()> {
+    require_scope(&claims, SCOPE_ADMIN)?;
+    app.mailer.send_test_email(&params.to).await?;
+    Ok(())
+}
+
 #[derive(Deserialize)]
 struct CreateAccessTokenQueryParams {
     public_key: String,
     impersonate: Option<String>,
+    name: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -207,9 +732,11 @@ struct CreateAccessTokenResponse {
 }
 
 async fn create_access_token(
+    headers: HeaderMap,
     Path(login): Path<String>,
     Query(params): Query<CreateAccessTokenQueryParams>,
     Extension(app): Extension<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
 ) -> Result<Json<CreateAccessTokenResponse>> {
     //     request.require_token().await?;
 
@@ -219,11 +746,24 @@ async fn create_access_token(
         .await?
         .ok_or_else(|| anyhow!("user not found"))?;
 
-    let mut user_id = user.id;
+    // Minting a token for `login` is only allowed for that user themselves,
+    // or for an admin acting on their behalf.
+    require_owner_or_admin(user.id, &claims)?;
+
+    let mut target_user = user.clone();
     if let Some(impersonate) = params.impersonate {
-        if user.admin {
+        if require_scope(&claims, SCOPE_ADMIN).is_ok() {
             if let Some(impersonated_user) = app.db.get_user_by_github_login(&impersonate).await? {
-                user_id = impersonated_user.id;
+                target_user = impersonated_user;
+                app.db
+                    .log_event(
+                        claims.sub,
+                        "user.impersonate",
+                        target_user.id.to_string(),
+                        json!({ "impersonated_login": impersonate }),
+                        client_ip(&headers),
+                    )
+                    .await?;
             } else {
                 return Err(Error::Http(
                     StatusCode::UNPROCESSABLE_ENTITY,
@@ -233,17 +773,208 @@ async fn create_access_token(
         } else {
             return Err(Error::Http(
                 StatusCode::UNAUTHORIZED,
-                format!("you do not have permission to impersonate other users"),
+                "you do not have permission to impersonate other users".to_string(),
             ));
         }
     }
 
-    let access_token = auth::create_access_token(app.db.as_ref(), user_id).await?;
+    if target_user.deactivated {
+        return Err(Error::Http(
+            StatusCode::UNAUTHORIZED,
+            "this account has been disabled".to_string(),
+        ));
+    }
+
+    let scopes = if target_user.admin {
+        vec![SCOPE_ADMIN.to_string()]
+    } else {
+        vec!["users:read".to_string()]
+    };
+    let name = params.name.unwrap_or_else(|| "default".to_string());
+    let token_id = app
+        .db
+        .create_access_token_record(target_user.id, &name)
+        .await?;
+    let access_token = sign_access_token(&app.jwt_secret, target_user.id, token_id, scopes)?;
     let encrypted_access_token =
         auth::encrypt_access_token(&access_token, params.public_key.clone())?;
 
     Ok(Json(CreateAccessTokenResponse {
-        user_id,
+        user_id: target_user.id,
         encrypted_access_token,
     }))
 }
+
+/// Returns a `403` unless `claims` belongs to `user_id` themselves or holds
+/// the `admin` scope. This is the ownership check every access-token
+/// management route (minting, listing, revoking) must apply.
+fn require_owner_or_admin(user_id: UserId, claims: &Claims) -> Result<()> {
+    if claims.sub != user_id {
+        require_scope(claims, SCOPE_ADMIN)?;
+    }
+    Ok(())
+}
+
+/// Loads `login`'s user record and checks that `claims` is allowed to manage
+/// their access tokens: either they're the owner, or they hold the `admin`
+/// scope.
+async fn authorize_access_token_management(
+    app: &AppState,
+    login: &str,
+    claims: &Claims,
+) -> Result<User> {
+    let user = app
+        .db
+        .get_user_by_github_login(login)
+        .await?
+        .ok_or_else(|| anyhow!("user not found"))?;
+    require_owner_or_admin(user.id, claims)?;
+    Ok(user)
+}
+
+async fn get_access_tokens(
+    Path(login): Path<String>,
+    Extension(app): Extension<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<Vec<AccessToken>>> {
+    let user = authorize_access_token_management(&app, &login, &claims).await?;
+    Ok(Json(app.db.get_access_tokens(user.id).await?))
+}
+
+async fn revoke_access_token(
+    headers: HeaderMap,
+    Path((login, id)): Path<(String, i32)>,
+    Extension(app): Extension<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+) -> Result<()> {
+    let user = authorize_access_token_management(&app, &login, &claims).await?;
+    app.db.revoke_access_token(user.id, id).await?;
+
+    app.db
+        .log_event(
+            claims.sub,
+            "access_token.revoke",
+            id.to_string(),
+            json!({ "user_id": user.id }),
+            client_ip(&headers),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims_with_scopes(scopes: &[&str]) -> Claims {
+        Claims {
+            sub: UserId(1),
+            jti: 1,
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+            iat: 0,
+            exp: 0,
+        }
+    }
+
+    #[test]
+    fn require_scope_allows_exact_match() {
+        let claims = claims_with_scopes(&["users:write"]);
+        assert!(require_scope(&claims, "users:write").is_ok());
+    }
+
+    #[test]
+    fn require_scope_rejects_missing_scope() {
+        let claims = claims_with_scopes(&["users:write"]);
+        assert!(require_scope(&claims, SCOPE_ADMIN).is_err());
+    }
+
+    #[test]
+    fn require_scope_admin_implies_every_scope() {
+        let claims = claims_with_scopes(&[SCOPE_ADMIN]);
+        assert!(require_scope(&claims, "invites:write").is_ok());
+    }
+
+    #[test]
+    fn require_owner_or_admin_allows_owner() {
+        let claims = claims_with_scopes(&["users:read"]);
+        assert!(require_owner_or_admin(UserId(1), &claims).is_ok());
+    }
+
+    #[test]
+    fn require_owner_or_admin_rejects_non_owner_without_admin_scope() {
+        let claims = claims_with_scopes(&["users:read"]);
+        assert!(require_owner_or_admin(UserId(2), &claims).is_err());
+    }
+
+    #[test]
+    fn require_owner_or_admin_allows_admin_scope_for_others() {
+        let claims = claims_with_scopes(&[SCOPE_ADMIN]);
+        assert!(require_owner_or_admin(UserId(2), &claims).is_ok());
+    }
+
+    #[test]
+    fn sign_and_decode_round_trip() {
+        let token = sign_access_token("secret", UserId(42), 7, vec![SCOPE_ADMIN.to_string()])
+            .expect("signing should succeed");
+
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.leeway = CLOCK_SKEW_LEEWAY_SECS;
+        let claims = decode::<Claims>(&token, &DecodingKey::from_secret(b"secret"), &validation)
+            .expect("decoding with the right secret should succeed")
+            .claims;
+
+        assert_eq!(claims.sub, UserId(42));
+        assert_eq!(claims.jti, 7);
+        assert!(claims.has_scope("users:write"));
+    }
+
+    #[test]
+    fn decode_rejects_wrong_secret() {
+        let token =
+            sign_access_token("secret", UserId(42), 7, vec![]).expect("signing should succeed");
+
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.leeway = CLOCK_SKEW_LEEWAY_SECS;
+        let result = decode::<Claims>(&token, &DecodingKey::from_secret(b"wrong"), &validation);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn decode_rejects_expired_token() {
+        let claims = Claims {
+            sub: UserId(1),
+            jti: 1,
+            scopes: vec![],
+            iat: 0,
+            exp: 1,
+        };
+        let token = encode(
+            &Header::new(Algorithm::HS256),
+            &claims,
+            &EncodingKey::from_secret(b"secret"),
+        )
+        .expect("signing should succeed");
+
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.leeway = CLOCK_SKEW_LEEWAY_SECS;
+        let result = decode::<Claims>(&token, &DecodingKey::from_secret(b"secret"), &validation);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn client_ip_reads_last_forwarded_for_entry() {
+        // The rightmost entry is the one appended by our own load balancer;
+        // everything to its left could have been set by the client.
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "1.2.3.4, 5.6.7.8".parse().unwrap());
+        assert_eq!(client_ip(&headers).as_deref(), Some("5.6.7.8"));
+    }
+
+    #[test]
+    fn client_ip_missing_header() {
+        assert_eq!(client_ip(&HeaderMap::new()), None);
+    }
+}